@@ -2,10 +2,26 @@
 /// numbers ("population").
 ///
 /// This can be used to estimate the standard error of the mean.
+///
+/// With the `serde` feature enabled, this type implements `Serialize` and
+/// `Deserialize`, so a partial accumulator can be checkpointed and resumed.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Skewness {
-    /// Estimator of mean and variance.
-    avg: MeanWithError,
+    /// Number of samples.
+    n: u64,
+    /// Sum of the weights of the samples seen so far.
+    ///
+    /// Equal to `n` for unweighted data.
+    weight_sum: f64,
+    /// Sum of the squared weights of the samples seen so far.
+    ///
+    /// Used to calculate the Kish effective sample size.
+    weight_sum_2: f64,
+    /// Estimate of the mean of the population.
+    mean: f64,
+    /// Intermediate sum of squares for calculating the variance.
+    sum_2: f64,
     /// Intermediate sum of cubes for calculating the skewness.
     sum_3: f64,
 }
@@ -15,7 +31,11 @@ impl Skewness {
     #[inline]
     pub fn new() -> Skewness {
         Skewness {
-            avg: MeanWithError::new(),
+            n: 0,
+            weight_sum: 0.,
+            weight_sum_2: 0.,
+            mean: 0.,
+            sum_2: 0.,
             sum_3: 0.,
         }
     }
@@ -23,41 +43,49 @@ impl Skewness {
     /// Add an observation sampled from the population.
     #[inline]
     pub fn add(&mut self, x: f64) {
-        let delta = x - self.mean();
-        self.increment();
-        let n = f64::approx_from(self.len()).unwrap();
-        self.add_inner(delta, delta/n);
+        self.add_weighted(x, 1.);
     }
 
-    /// Increment the sample size.
+    /// Add an observation sampled from the population with a given weight.
     ///
-    /// This does not update anything else.
+    /// Weights are treated as frequency weights, i.e. a weight of `w` is
+    /// equivalent to `w` unweighted observations of `x`; they do not need to
+    /// sum to one. Passing a weight of `1.` for every sample is equivalent to
+    /// [`add`](#method.add).
     #[inline]
-    fn increment(&mut self) {
-        self.avg.increment();
+    pub fn add_weighted(&mut self, x: f64, weight: f64) {
+        let delta = x - self.mean;
+        self.n += 1;
+        let weight_sum_old = self.weight_sum;
+        self.weight_sum += weight;
+        self.weight_sum_2 += weight * weight;
+        let r = delta * weight / self.weight_sum;
+        self.add_inner(delta, r, weight_sum_old, weight);
     }
 
-    /// Add an observation given an already calculated difference from the mean
-    /// divided by the number of samples, assuming the inner count of the sample
-    /// size was already updated.
+    /// Add an observation given the resulting increment to the mean and the
+    /// running sum of weights before and the weight of this observation,
+    /// assuming the sums of weights were already updated.
     ///
     /// This is useful for avoiding unnecessary divisions in the inner loop.
     #[inline]
-    fn add_inner(&mut self, delta: f64, delta_n: f64) {
-        // This algorithm was suggested by Terriberry.
+    fn add_inner(&mut self, delta: f64, r: f64, weight_sum_old: f64, weight: f64) {
+        // Generalization of the algorithm suggested by Terriberry to weighted
+        // samples, following West.
         //
         // See https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance.
-        let n = f64::approx_from(self.len()).unwrap();
-        let term = delta * delta_n * (n - 1.);
-        self.sum_3 += term * delta_n * (n - 2.)
-            - 3.*delta_n * self.avg.sum_2;
-        self.avg.add_inner(delta_n);
+        let term = weight_sum_old * delta * r;
+        self.sum_3 += weight*delta*delta*delta * weight_sum_old*(weight_sum_old - weight)
+            / (self.weight_sum*self.weight_sum)
+            - 3.*r * self.sum_2;
+        self.sum_2 += term;
+        self.mean += r;
     }
 
     /// Determine whether the sample is empty.
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.avg.is_empty()
+        self.n == 0
     }
 
     /// Estimate the mean of the population.
@@ -65,21 +93,41 @@ impl Skewness {
     /// Returns 0 for an empty sample.
     #[inline]
     pub fn mean(&self) -> f64 {
-        self.avg.mean()
+        self.mean
     }
 
     /// Return the sample size.
     #[inline]
     pub fn len(&self) -> u64 {
-        self.avg.len()
+        self.n
+    }
+
+    /// Return the Kish effective sample size, `(sum w)^2 / sum(w^2)`.
+    ///
+    /// This is equal to [`len`](#method.len) for unweighted samples. Note
+    /// that [`error_mean`](#method.error_mean) does not use this value: it
+    /// follows the frequency-weight convention and divides by the sum of
+    /// weights instead.
+    ///
+    /// Returns 0 for an empty sample.
+    #[inline]
+    pub fn effective_len(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+        self.weight_sum * self.weight_sum / self.weight_sum_2
     }
 
     /// Calculate the sample variance.
     ///
-    /// This is an unbiased estimator of the variance of the population.
+    /// This is an unbiased estimator of the variance of the population,
+    /// treating weights as frequency weights.
     #[inline]
     pub fn sample_variance(&self) -> f64 {
-        self.avg.sample_variance()
+        if self.n < 2 {
+            return 0.;
+        }
+        self.sum_2 / (self.weight_sum - 1.)
     }
 
     /// Calculate the population variance of the sample.
@@ -87,13 +135,24 @@ impl Skewness {
     /// This is a biased estimator of the variance of the population.
     #[inline]
     pub fn population_variance(&self) -> f64 {
-        self.avg.population_variance()
+        if self.is_empty() {
+            return 0.;
+        }
+        self.sum_2 / self.weight_sum
     }
 
     /// Estimate the standard error of the mean of the population.
+    ///
+    /// Consistent with [`sample_variance`](#method.sample_variance), weights
+    /// are treated as frequency weights, so this divides by the sum of
+    /// weights rather than the Kish effective sample size returned by
+    /// [`effective_len`](#method.effective_len).
     #[inline]
     pub fn error_mean(&self) -> f64 {
-        self.avg.error()
+        if self.is_empty() {
+            return 0.;
+        }
+        (self.sample_variance() / self.weight_sum).sqrt()
     }
 
     /// Estimate the skewness of the population.
@@ -102,25 +161,58 @@ impl Skewness {
         if self.sum_3 == 0. {
             return 0.;
         }
-        let n = f64::approx_from(self.len()).unwrap();
-        let sum_2 = self.avg.sum_2;
-        debug_assert_ne!(sum_2, 0.);
-        n.sqrt() * self.sum_3 / (sum_2*sum_2*sum_2).sqrt()
+        debug_assert_ne!(self.sum_2, 0.);
+        self.weight_sum.sqrt() * self.sum_3 / (self.sum_2*self.sum_2*self.sum_2).sqrt()
     }
 
     /// Merge another sample into this one.
     #[inline]
     pub fn merge(&mut self, other: &Skewness) {
-        let len_self = f64::approx_from(self.len()).unwrap();
-        let len_other = f64::approx_from(other.len()).unwrap();
-        let len_total = len_self + len_other;
-        let delta = other.mean() - self.mean();
-        let delta_n = delta / len_total;
+        if other.is_empty() {
+            return;
+        }
+        let weight_sum_total = self.weight_sum + other.weight_sum;
+        let delta = other.mean - self.mean;
+        let delta_n = delta / weight_sum_total;
         self.sum_3 += other.sum_3
-            + delta*delta_n*delta_n * len_self*len_other*(len_self - len_other)
-            + 3.*delta_n * (len_self * other.avg.sum_2 - len_other * self.avg.sum_2);
-        self.avg.merge(&other.avg);
+            + delta*delta_n*delta_n * self.weight_sum*other.weight_sum*(self.weight_sum - other.weight_sum)
+            + 3.*delta_n * (self.weight_sum * other.sum_2 - other.weight_sum * self.sum_2);
+        self.sum_2 += other.sum_2 + delta*delta_n * self.weight_sum*other.weight_sum;
+        self.mean += delta_n * other.weight_sum;
+        self.n += other.n;
+        self.weight_sum = weight_sum_total;
+        self.weight_sum_2 += other.weight_sum_2;
     }
 }
 
 impl_from_iterator!(Skewness);
+
+// Requires `serde_json` as a dev-dependency in Cargo.toml.
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::Skewness;
+
+    #[test]
+    fn serde_round_trip_resumes_stream() {
+        let data = [1., 2., 3., 4., 5., 6., 7., 8.];
+
+        let mut uninterrupted = Skewness::new();
+        for &x in &data {
+            uninterrupted.add(x);
+        }
+
+        let mut before_checkpoint = Skewness::new();
+        for &x in &data[..4] {
+            before_checkpoint.add(x);
+        }
+        let serialized = serde_json::to_string(&before_checkpoint).unwrap();
+        let mut resumed: Skewness = serde_json::from_str(&serialized).unwrap();
+        for &x in &data[4..] {
+            resumed.add(x);
+        }
+
+        assert_eq!(resumed.len(), uninterrupted.len());
+        assert_eq!(resumed.mean(), uninterrupted.mean());
+        assert_eq!(resumed.skewness(), uninterrupted.skewness());
+    }
+}